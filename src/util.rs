@@ -3,7 +3,9 @@ use chrono::NaiveDateTime;
 use eyre::eyre;
 use eyre::Result;
 use glob::glob;
+use glob::Pattern;
 use lazy_static::lazy_static;
+use rayon::prelude::*;
 use regex::Regex;
 use scraper::{Html, Selector};
 use std::collections::HashMap;
@@ -11,6 +13,7 @@ use std::collections::HashSet;
 use std::{fs, time::UNIX_EPOCH};
 use tera::Tera;
 use tracing::debug;
+use walkdir::WalkDir;
 
 use crate::site_url::{HrefUrl, ImgUrl};
 
@@ -65,6 +68,67 @@ pub fn copy_files_to(pattern: &str, target_dir: &Utf8Path) -> Result<()> {
     Ok(())
 }
 
+/// Include/exclude glob rules used when walking a directory with `copy_dir_filtered`.
+///
+/// A file is copied when it matches at least one `include` rule (or `include` is empty)
+/// and none of the `exclude` rules.
+pub struct CopyRules {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+}
+
+impl CopyRules {
+    pub fn new(include: &[&str], exclude: &[&str]) -> Result<Self> {
+        Ok(Self {
+            include: include
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<_, _>>()?,
+            exclude: exclude
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn allows(&self, path: &Utf8Path) -> bool {
+        let name = path.as_str();
+        if self.exclude.iter().any(|pattern| pattern.matches(name)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(name))
+    }
+}
+
+/// Recursively copy every file under `root` into `target_dir`, keeping the directory
+/// structure and honoring `rules`, returning the copied destination paths.
+pub fn copy_dir_filtered(
+    root: &Utf8Path,
+    target_dir: &Utf8Path,
+    rules: &CopyRules,
+) -> Result<Vec<Utf8PathBuf>> {
+    let mut copied = Vec::new();
+
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = Utf8Path::from_path(entry.path()).expect("Non-utf8 path");
+        let rel_path = path.strip_prefix(root)?;
+        if !rules.allows(rel_path) {
+            continue;
+        }
+
+        let to = target_dir.join(rel_path);
+        copy_file(path, &to)?;
+        copied.push(to);
+    }
+
+    Ok(copied)
+}
+
 pub fn load_templates(pattern: &str) -> Result<Tera> {
     let mut templates = Tera::new(pattern)?;
     templates.autoescape_on(vec![]);
@@ -118,16 +182,31 @@ pub struct ParsedFile {
     pub links: HashSet<HrefUrl>,
     pub imgs: HashSet<ImgUrl>,
     pub fragments: HashSet<String>,
+    pub duplicate_ids: Vec<String>,
+    pub redirect: Option<HrefUrl>,
 }
 
 pub type ParsedFiles = HashMap<Utf8PathBuf, ParsedFile>;
 
 pub fn parse_html_files(output_dir: &Utf8Path) -> Result<ParsedFiles> {
-    glob(&format!("{}/**/*.html", output_dir))
+    let paths: Vec<_> = glob(&format!("{}/**/*.html", output_dir))
         .unwrap()
         .flatten()
+        .collect();
+
+    // Reading every file is the part that actually benefits from parallelism; `Html` isn't
+    // `Send`, so parsing and selecting stays sequential per item below.
+    let contents: Result<Vec<(_, String)>, std::io::Error> = paths
+        .into_par_iter()
         .map(|path| {
             let content = fs::read_to_string(&path)?;
+            Ok((path, content))
+        })
+        .collect();
+
+    contents?
+        .into_iter()
+        .map(|(path, content)| {
             let html = Html::parse_document(&content);
             let path = Utf8PathBuf::from_path_buf(path).unwrap();
 
@@ -137,7 +216,9 @@ pub fn parse_html_files(output_dir: &Utf8Path) -> Result<ParsedFiles> {
                 .map_err(|err| eyre!("Error parsing file `{}`:\n  {}", path, err))?;
             let imgs = collect_imgs(&html)
                 .map_err(|err| eyre!("Error parsing file `{}`:\n  {}", path, err))?;
-            let fragments = collect_fragments(&html)
+            let (fragments, duplicate_ids) = collect_fragments(&html)
+                .map_err(|err| eyre!("Error parsing file `{}`:\n  {}", path, err))?;
+            let redirect = collect_redirect(&html)
                 .map_err(|err| eyre!("Error parsing file `{}`:\n  {}", path, err))?;
 
             Ok((
@@ -149,6 +230,8 @@ pub fn parse_html_files(output_dir: &Utf8Path) -> Result<ParsedFiles> {
                     links,
                     imgs,
                     fragments,
+                    duplicate_ids,
+                    redirect,
                 },
             ))
         })
@@ -191,15 +274,208 @@ pub fn collect_imgs(document: &Html) -> Result<HashSet<ImgUrl>> {
     Ok(imgs)
 }
 
-pub fn collect_fragments(document: &Html) -> Result<HashSet<String>> {
+/// Collects the redirect target of a `<meta http-equiv="refresh" content="0; url=...">`
+/// stub page, if `document` is one.
+pub fn collect_redirect(document: &Html) -> Result<Option<HrefUrl>> {
+    let selector = Selector::parse(r#"meta[http-equiv="refresh" i]"#).unwrap();
+    let Some(element) = document.select(&selector).next() else {
+        return Ok(None);
+    };
+    let Some(content) = element.value().attr("content") else {
+        return Ok(None);
+    };
+    let Some(idx) = content.to_ascii_lowercase().find("url=") else {
+        return Ok(None);
+    };
+    let url = &content[idx + "url=".len()..];
+    let url = url.trim().trim_matches(|c| c == '\'' || c == '"');
+
+    let href = HrefUrl::parse(url).map_err(|err| {
+        eyre!(
+            "Error parsing meta-refresh redirect target `{}`:\n  {}",
+            url,
+            err
+        )
+    })?;
+    Ok(Some(href))
+}
+
+/// Collects the ids declared in `document` as fragments (e.g. `#foo`).
+///
+/// Ids are supposed to be unique within an html document, so any id seen more than once
+/// is also returned, keyed by its `#fragment` form, so callers can report it.
+pub fn collect_fragments(document: &Html) -> Result<(HashSet<String>, Vec<String>)> {
     let selector = Selector::parse("[id]").unwrap();
     let mut fragments = HashSet::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
     for element in document.select(&selector) {
         if let Some(id) = element.value().attr("id") {
-            fragments.insert(format!("#{id}"));
+            let fragment = format!("#{id}");
+            *counts.entry(fragment.clone()).or_insert(0) += 1;
+            fragments.insert(fragment);
         }
     }
-    Ok(fragments)
+
+    let mut duplicates: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(fragment, _)| fragment)
+        .collect();
+    duplicates.sort();
+
+    Ok((fragments, duplicates))
+}
+
+/// A broken link, broken image or duplicate id discovered while validating the output site.
+#[derive(Debug)]
+pub enum LinkError {
+    /// An `<a href>` that doesn't resolve to any file in the output dir.
+    BrokenLink { href: HrefUrl },
+    /// An `<a href="...#frag">` whose target exists but is missing the fragment.
+    BrokenFragment { href: HrefUrl },
+    /// An `<img src>` that doesn't resolve to a file on disk.
+    BrokenImg { src: ImgUrl },
+    /// An id that appears more than once in the same document.
+    DuplicateId { fragment: String },
+    /// A link that resolves through a chain of meta-refresh redirects back to itself.
+    RedirectCycle { href: HrefUrl },
+    /// A link that resolves to a meta-refresh redirect whose own target is missing.
+    DanglingRedirect { href: HrefUrl },
+}
+
+/// All the link errors found in a single source file.
+#[derive(Debug)]
+pub struct FileLinkErrors {
+    pub path: Utf8PathBuf,
+    pub errors: Vec<LinkError>,
+}
+
+/// Validate that every link and image collected in `files` resolves to something real.
+pub fn check_links(files: &ParsedFiles, output_dir: &Utf8Path) -> Vec<FileLinkErrors> {
+    let mut result = Vec::new();
+
+    for file in files.values() {
+        let mut errors = Vec::new();
+
+        for fragment in &file.duplicate_ids {
+            errors.push(LinkError::DuplicateId {
+                fragment: fragment.clone(),
+            });
+        }
+
+        for href in &file.links {
+            let target = resolve_href_path(href, &file.path, output_dir);
+            match resolve_redirects(target, output_dir, files) {
+                Resolved::File(target) => {
+                    if let Some(fragment) = href_fragment(href) {
+                        if !target.fragments.contains(&fragment) {
+                            errors.push(LinkError::BrokenFragment { href: href.clone() });
+                        }
+                    }
+                }
+                Resolved::Broken => errors.push(LinkError::BrokenLink { href: href.clone() }),
+                Resolved::DanglingRedirect => {
+                    errors.push(LinkError::DanglingRedirect { href: href.clone() })
+                }
+                Resolved::Cycle => errors.push(LinkError::RedirectCycle { href: href.clone() }),
+            }
+        }
+
+        for img in &file.imgs {
+            if !resolve_img(img, &file.path, output_dir).is_file() {
+                errors.push(LinkError::BrokenImg { src: img.clone() });
+            }
+        }
+
+        if !errors.is_empty() {
+            result.push(FileLinkErrors {
+                path: file.path.clone(),
+                errors,
+            });
+        }
+    }
+
+    result
+}
+
+/// The outcome of resolving a link target, following any meta-refresh redirect chain.
+enum Resolved<'a> {
+    File(&'a ParsedFile),
+    Broken,
+    DanglingRedirect,
+    Cycle,
+}
+
+/// Resolve `target` to the `ParsedFile` it points at, following redirect stubs until
+/// a non-redirecting page is found.
+fn resolve_redirects<'a>(
+    mut target: Utf8PathBuf,
+    output_dir: &Utf8Path,
+    files: &'a ParsedFiles,
+) -> Resolved<'a> {
+    let mut seen = HashSet::new();
+
+    loop {
+        let Some(file) = files.get(&target) else {
+            return if seen.is_empty() {
+                Resolved::Broken
+            } else {
+                Resolved::DanglingRedirect
+            };
+        };
+
+        if !seen.insert(target.clone()) {
+            return Resolved::Cycle;
+        }
+
+        match &file.redirect {
+            Some(redirect) => target = resolve_href_path(redirect, &file.path, output_dir),
+            None => return Resolved::File(file),
+        }
+    }
+}
+
+/// Turn an href into the on-disk path it should resolve to, mapping e.g. `/foo/` to
+/// `<output_dir>/foo/index.html`.
+fn resolve_href_path(href: &HrefUrl, source: &Utf8Path, output_dir: &Utf8Path) -> Utf8PathBuf {
+    let (path, _fragment) = href_path_and_fragment(href);
+
+    // A bare fragment (e.g. `#top`) links back to the page it's found in.
+    if path.is_empty() {
+        return source.to_path_buf();
+    }
+
+    let path = match path.strip_prefix('/') {
+        Some(rel) => output_dir.join(rel),
+        None => source.parent().unwrap_or(output_dir).join(path),
+    };
+
+    if path.extension().is_none() {
+        path.join("index.html")
+    } else {
+        path
+    }
+}
+
+/// Turn an img src into the on-disk path it should resolve to.
+fn resolve_img(src: &ImgUrl, source: &Utf8Path, output_dir: &Utf8Path) -> Utf8PathBuf {
+    let path = src.to_string();
+    match path.strip_prefix('/') {
+        Some(rel) => output_dir.join(rel),
+        None => source.parent().unwrap_or(output_dir).join(path),
+    }
+}
+
+fn href_fragment(href: &HrefUrl) -> Option<String> {
+    href_path_and_fragment(href).1
+}
+
+fn href_path_and_fragment(href: &HrefUrl) -> (String, Option<String>) {
+    let href = href.to_string();
+    match href.split_once('#') {
+        Some((path, frag)) => (path.to_string(), Some(format!("#{frag}"))),
+        None => (href, None),
+    }
 }
 
 #[cfg(test)]
@@ -233,4 +509,180 @@ mod tests {
         assert_eq!(slugify("-trimmed--"), "trimmed");
         assert_eq!(slugify("_trimmed__"), "trimmed");
     }
+
+    #[test]
+    fn test_copy_dir_filtered_respects_nested_rules() {
+        let base = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join("util_copy_dir_filtered_test");
+        let root = base.join("root");
+        let target = base.join("target");
+        let _ = fs::remove_dir_all(&base);
+
+        fs::create_dir_all(root.join("fonts")).unwrap();
+        fs::create_dir_all(root.join("drafts")).unwrap();
+        fs::write(root.join("fonts/a.woff"), "font").unwrap();
+        fs::write(root.join("drafts/b.md"), "draft").unwrap();
+
+        let rules = CopyRules::new(&["fonts/*.woff"], &[]).unwrap();
+        let copied = copy_dir_filtered(&root, &target, &rules).unwrap();
+
+        assert_eq!(copied, vec![target.join("fonts/a.woff")]);
+        assert!(target.join("fonts/a.woff").is_file());
+        assert!(!target.join("drafts/b.md").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    fn fake_parsed_file(path: &str, redirect: Option<&str>) -> (Utf8PathBuf, ParsedFile) {
+        let path = Utf8PathBuf::from(path);
+        let file = ParsedFile {
+            path: path.clone(),
+            html: Html::parse_document(""),
+            content: String::new(),
+            links: HashSet::new(),
+            imgs: HashSet::new(),
+            fragments: HashSet::new(),
+            duplicate_ids: Vec::new(),
+            redirect: redirect.map(|href| HrefUrl::parse(href).unwrap()),
+        };
+        (path, file)
+    }
+
+    #[test]
+    fn test_resolve_href_path_absolute() {
+        let href = HrefUrl::parse("/foo/").unwrap();
+        let source = Utf8Path::new("/out/bar/page.html");
+        let output_dir = Utf8Path::new("/out");
+        assert_eq!(
+            resolve_href_path(&href, source, output_dir),
+            Utf8PathBuf::from("/out/foo/index.html")
+        );
+    }
+
+    #[test]
+    fn test_resolve_href_path_relative() {
+        let href = HrefUrl::parse("sibling.html").unwrap();
+        let source = Utf8Path::new("/out/bar/page.html");
+        let output_dir = Utf8Path::new("/out");
+        assert_eq!(
+            resolve_href_path(&href, source, output_dir),
+            Utf8PathBuf::from("/out/bar/sibling.html")
+        );
+    }
+
+    #[test]
+    fn test_resolve_href_path_bare_fragment_resolves_to_source() {
+        let href = HrefUrl::parse("#top").unwrap();
+        let source = Utf8Path::new("/out/bar/page.html");
+        let output_dir = Utf8Path::new("/out");
+        assert_eq!(
+            resolve_href_path(&href, source, output_dir),
+            source.to_path_buf()
+        );
+    }
+
+    #[test]
+    fn test_check_links_broken_link_and_fragment() {
+        let output_dir = Utf8Path::new("/out");
+        let mut files = ParsedFiles::new();
+
+        let (path, mut file) = fake_parsed_file("/out/page.html", None);
+        file.links.insert(HrefUrl::parse("/missing.html").unwrap());
+        file.links
+            .insert(HrefUrl::parse("/page.html#nope").unwrap());
+        files.insert(path.clone(), file);
+
+        let errors = check_links(&files, output_dir);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, path);
+        assert_eq!(errors[0].errors.len(), 2);
+        assert!(errors[0]
+            .errors
+            .iter()
+            .any(|err| matches!(err, LinkError::BrokenLink { .. })));
+        assert!(errors[0]
+            .errors
+            .iter()
+            .any(|err| matches!(err, LinkError::BrokenFragment { .. })));
+    }
+
+    #[test]
+    fn test_collect_fragments_duplicates() {
+        let html = Html::parse_document(
+            r#"<html><body><div id="a"></div><div id="b"></div><div id="a"></div></body></html>"#,
+        );
+        let (fragments, duplicates) = collect_fragments(&html).unwrap();
+        assert!(fragments.contains("#a"));
+        assert!(fragments.contains("#b"));
+        assert_eq!(duplicates, vec!["#a".to_string()]);
+    }
+
+    #[test]
+    fn test_collect_fragments_duplicates_are_sorted() {
+        let html = Html::parse_document(
+            r#"<html><body><div id="z"></div><div id="z"></div><div id="a"></div><div id="a"></div></body></html>"#,
+        );
+        let (_, duplicates) = collect_fragments(&html).unwrap();
+        assert_eq!(duplicates, vec!["#a".to_string(), "#z".to_string()]);
+    }
+
+    #[test]
+    fn test_collect_fragments_no_duplicates() {
+        let html = Html::parse_document(r#"<html><body><div id="a"></div></body></html>"#);
+        let (_, duplicates) = collect_fragments(&html).unwrap();
+        assert!(duplicates.is_empty());
+    }
+
+    #[test]
+    fn test_collect_redirect_lowercase() {
+        let html = Html::parse_document(
+            r#"<html><head><meta http-equiv="refresh" content="0; url=/target.html"></head></html>"#,
+        );
+        let redirect = collect_redirect(&html).unwrap();
+        assert!(redirect.is_some());
+    }
+
+    #[test]
+    fn test_collect_redirect_uppercase() {
+        let html = Html::parse_document(
+            r#"<html><head><meta http-equiv="refresh" content="0;URL=/target.html"></head></html>"#,
+        );
+        let redirect = collect_redirect(&html).unwrap();
+        assert!(redirect.is_some());
+    }
+
+    #[test]
+    fn test_collect_redirect_none() {
+        let html = Html::parse_document(r#"<html><head></head><body>hi</body></html>"#);
+        assert!(collect_redirect(&html).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resolve_redirects_cycle() {
+        let output_dir = Utf8Path::new("/out");
+        let mut files = ParsedFiles::new();
+        let (path_a, file_a) = fake_parsed_file("/out/a/index.html", Some("/b/"));
+        let (path_b, file_b) = fake_parsed_file("/out/b/index.html", Some("/a/"));
+        files.insert(path_a.clone(), file_a);
+        files.insert(path_b, file_b);
+
+        assert!(matches!(
+            resolve_redirects(path_a, output_dir, &files),
+            Resolved::Cycle
+        ));
+    }
+
+    #[test]
+    fn test_resolve_redirects_dangling() {
+        let output_dir = Utf8Path::new("/out");
+        let mut files = ParsedFiles::new();
+        let (path_a, file_a) = fake_parsed_file("/out/a/index.html", Some("/missing/"));
+        files.insert(path_a.clone(), file_a);
+
+        assert!(matches!(
+            resolve_redirects(path_a, output_dir, &files),
+            Resolved::DanglingRedirect
+        ));
+    }
 }